@@ -4,9 +4,11 @@
 //
 // Author: Joerg Roedel <jroedel@suse.de>
 
+use super::features::cpu_type;
+use crate::types::CpuType;
 use crate::utils::immut_after_init::ImmutAfterInitRef;
 use bitflags::bitflags;
-use core::arch::x86_64::__cpuid;
+use core::arch::x86_64::{__cpuid, __cpuid_count};
 use cpuarch::snp_cpuid::SnpCpuidTable;
 use log;
 
@@ -62,9 +64,11 @@ pub fn cpuid(eax: u32) -> Option<CpuidResult> {
     }
 }
 
-#[allow(unreachable_code, unused)]
+/// Looks up `(eax, ecx, xcr0, xss)` in the `CPUID_PAGE` supplied by the
+/// hypervisor/VMM and returns the recorded result. Falls back to a native
+/// `CPUID`/`CPUID_COUNT` instruction when the table has no matching entry,
+/// which happens for leaves that do not depend on XCR0/XSS state.
 pub fn cpuid_table_raw(eax: u32, ecx: u32, xcr0: u64, xss: u64) -> Option<CpuidResult> {
-    panic!("cpuid_table_raw not supported");
     let count: usize = CPUID_PAGE.count as usize;
 
     for i in 0..count {
@@ -82,16 +86,22 @@ pub fn cpuid_table_raw(eax: u32, ecx: u32, xcr0: u64, xss: u64) -> Option<CpuidR
         }
     }
 
-    None
+    unsafe {
+        let result = __cpuid_count(eax, ecx);
+        Some(CpuidResult {
+            eax: result.eax,
+            ebx: result.ebx,
+            ecx: result.ecx,
+            edx: result.edx,
+        })
+    }
 }
 
 pub fn cpuid_table(eax: u32) -> Option<CpuidResult> {
     cpuid_table_raw(eax, 0, 0, 0)
 }
 
-#[allow(unreachable_code, unused)]
 pub fn dump_cpuid_table() {
-    panic!("dump_cpuid_table not supported");
     let count = CPUID_PAGE.count as usize;
 
     log::trace!("CPUID Table entry count: {}", count);
@@ -311,3 +321,147 @@ bitflags! {
         const LM            = 1 << 29;
     }
 }
+
+/// Identifies the L2 partition guest a CPUID lookup is being performed for,
+/// so masking can differ per partition instead of applying one blanket
+/// policy to every guest.
+pub type PartitionId = u32;
+
+/// A single entry of the L2 partition guest CPUID emulation policy: for the
+/// leaf/subleaf identified by `eax_in`/`ecx_in`, clear `clear_*` bits from
+/// the corresponding output register before handing the result to the
+/// guest. Bits are expressed in terms of the `Cpuid*` bitflags above so the
+/// policy table below stays readable.
+///
+/// `partition` scopes the entry: `None` applies it to every partition,
+/// `Some(id)` applies it only to that partition, layered on top of any
+/// matching `None` entry for the same leaf.
+#[derive(Clone, Copy, Debug)]
+struct CpuidMaskEntry {
+    partition: Option<PartitionId>,
+    eax_in: u32,
+    ecx_in: u32,
+    clear_eax: u32,
+    clear_ebx: u32,
+    clear_ecx: u32,
+    clear_edx: u32,
+}
+
+/// Feature bits hidden from L2 partition guests when running on a TDX host.
+/// This keeps the feature view the SVSM presents to guests stable and
+/// sanitized rather than leaking every capability of the underlying host.
+///
+/// The entries below (`partition: None`) apply to every partition.
+/// Individual partitions can be masked further by adding `Some(id)` entries
+/// for the same leaf, which are applied in addition to the blanket entry.
+static L2_CPUID_POLICY: &[CpuidMaskEntry] = &[
+    CpuidMaskEntry {
+        partition: None,
+        eax_in: 0x0000_0001,
+        ecx_in: 0,
+        clear_eax: 0,
+        clear_ebx: 0,
+        clear_ecx: Cpuid01Ecx::VMX.bits() | Cpuid01Ecx::SMX.bits(),
+        clear_edx: 0,
+    },
+    CpuidMaskEntry {
+        partition: None,
+        eax_in: 0x0000_0007,
+        ecx_in: 0,
+        clear_eax: 0,
+        clear_ebx: Cpuid07_0Ebx::SGX.bits(),
+        clear_ecx: Cpuid07_0Ecx::SGX_LC.bits(),
+        clear_edx: Cpuid07_0Edx::SGX_KEYS.bits(),
+    },
+    CpuidMaskEntry {
+        partition: None,
+        eax_in: 0x8000_0001,
+        ecx_in: 0,
+        clear_eax: 0,
+        clear_ebx: 0,
+        clear_ecx: 0,
+        clear_edx: Cpuid80000001Edx::GBPAGES.bits(),
+    },
+];
+
+fn apply_l2_cpuid_policy(partition: PartitionId, eax: u32, ecx: u32, result: &mut CpuidResult) {
+    for entry in L2_CPUID_POLICY {
+        if entry.eax_in != eax || entry.ecx_in != ecx {
+            continue;
+        }
+        match entry.partition {
+            None => (),
+            Some(id) if id == partition => (),
+            Some(_) => continue,
+        }
+        result.eax &= !entry.clear_eax;
+        result.ebx &= !entry.clear_ebx;
+        result.ecx &= !entry.clear_ecx;
+        result.edx &= !entry.clear_edx;
+    }
+}
+
+/// Looks up a CPUID leaf the same way [`cpuid_table_raw`] does, but when
+/// running on a TDX host additionally filters the result through the
+/// [`L2_CPUID_POLICY`] table, scoped to `partition`, before returning it.
+/// Intended for CPUID results handed to L2 partition guests, so each
+/// partition sees a sanitized feature set instead of whatever the host
+/// actually supports.
+pub fn cpuid_table_l2(
+    partition: PartitionId,
+    eax: u32,
+    ecx: u32,
+    xcr0: u64,
+    xss: u64,
+) -> Option<CpuidResult> {
+    let mut result = cpuid_table_raw(eax, ecx, xcr0, xss)?;
+
+    if cpu_type() == CpuType::Td {
+        apply_l2_cpuid_policy(partition, eax, ecx, &mut result);
+    }
+
+    Some(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn masked_result() -> CpuidResult {
+        CpuidResult {
+            eax: 0xffff_ffff,
+            ebx: 0xffff_ffff,
+            ecx: 0xffff_ffff,
+            edx: 0xffff_ffff,
+        }
+    }
+
+    #[test]
+    fn blanket_entry_masks_every_partition() {
+        for partition in [0, 1, 7] {
+            let mut result = masked_result();
+            apply_l2_cpuid_policy(partition, 0x0000_0001, 0, &mut result);
+            assert_eq!(result.ecx & Cpuid01Ecx::VMX.bits(), 0);
+            assert_eq!(result.ecx & Cpuid01Ecx::SMX.bits(), 0);
+        }
+    }
+
+    #[test]
+    fn unrelated_leaf_is_left_untouched() {
+        let mut result = masked_result();
+        apply_l2_cpuid_policy(0, 0x0000_0002, 0, &mut result);
+        assert_eq!(result.eax, 0xffff_ffff);
+        assert_eq!(result.ebx, 0xffff_ffff);
+        assert_eq!(result.ecx, 0xffff_ffff);
+        assert_eq!(result.edx, 0xffff_ffff);
+    }
+
+    #[test]
+    fn sgx_bits_are_cleared_from_leaf_7() {
+        let mut result = masked_result();
+        apply_l2_cpuid_policy(0, 0x0000_0007, 0, &mut result);
+        assert_eq!(result.ebx & Cpuid07_0Ebx::SGX.bits(), 0);
+        assert_eq!(result.ecx & Cpuid07_0Ecx::SGX_LC.bits(), 0);
+        assert_eq!(result.edx & Cpuid07_0Edx::SGX_KEYS.bits(), 0);
+    }
+}
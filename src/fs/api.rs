@@ -12,7 +12,7 @@ use crate::error::SvsmError;
 use crate::string::FixedString;
 
 /// Maximum supported length for a single filename
-const MAX_FILENAME_LENGTH: usize = 64;
+pub(crate) const MAX_FILENAME_LENGTH: usize = 64;
 pub type FileName = FixedString<MAX_FILENAME_LENGTH>;
 
 #[derive(Copy, Clone, Debug)]
@@ -20,6 +20,11 @@ pub enum FsError {
     Inval,
     FileExists,
     FileNotFound,
+    NotADirectory,
+    IsADirectory,
+    NameTooLong,
+    NoSpace,
+    ReadOnly,
 }
 
 macro_rules! impl_fs_err {
@@ -34,6 +39,26 @@ impl FsError {
     impl_fs_err!(inval, Inval);
     impl_fs_err!(file_exists, FileExists);
     impl_fs_err!(file_not_found, FileNotFound);
+    impl_fs_err!(not_a_directory, NotADirectory);
+    impl_fs_err!(is_a_directory, IsADirectory);
+    impl_fs_err!(name_too_long, NameTooLong);
+    impl_fs_err!(no_space, NoSpace);
+    impl_fs_err!(read_only, ReadOnly);
+
+    /// A stable numeric code for this error, so it can cross a future guest
+    /// syscall boundary without losing meaning.
+    pub fn as_errno(&self) -> i32 {
+        match self {
+            Self::Inval => 1,
+            Self::FileExists => 2,
+            Self::FileNotFound => 3,
+            Self::NotADirectory => 4,
+            Self::IsADirectory => 5,
+            Self::NameTooLong => 6,
+            Self::NoSpace => 7,
+            Self::ReadOnly => 8,
+        }
+    }
 }
 
 pub trait File {
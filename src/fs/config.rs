@@ -0,0 +1,255 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//
+// Copyright (c) 2023 SUSE LLC
+//
+// Author: Joerg Roedel <jroedel@suse.de>
+
+//! A typed `key=value` store layered on top of the [`File`] trait. Every
+//! mutating call rewrites the complete record set through a scratch-then-
+//! commit sequence ([`ConfigStore::persist`]) so a crash or power loss
+//! mid-write never leaves the on-disk header pointing at a partial record
+//! set.
+
+extern crate alloc;
+use alloc::collections::BTreeMap;
+use alloc::string::{String, ToString};
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+
+use spin::RwLock;
+
+use super::api::{File, FsError};
+use crate::error::SvsmError;
+
+const MAGIC: u32 = 0x4346_4731; // "CFG1"
+const HEADER_SIZE: usize = 24;
+const PRIMARY_SLOT_OFFSET: usize = HEADER_SIZE;
+
+/// On-disk header pointing at wherever the current, committed record set
+/// lives (`offset`/`len`). A reader only ever trusts bytes reachable
+/// through the header, so a new record set only becomes visible once this
+/// header is rewritten to point at it.
+#[derive(Clone, Copy)]
+struct Header {
+    offset: u64,
+    len: u64,
+}
+
+impl Header {
+    fn parse(buf: &[u8]) -> Option<Self> {
+        if u32::from_le_bytes(buf[0..4].try_into().unwrap()) != MAGIC {
+            return None;
+        }
+        Some(Header {
+            offset: u64::from_le_bytes(buf[8..16].try_into().unwrap()),
+            len: u64::from_le_bytes(buf[16..24].try_into().unwrap()),
+        })
+    }
+
+    fn serialize(&self) -> [u8; HEADER_SIZE] {
+        let mut buf = [0u8; HEADER_SIZE];
+        buf[0..4].copy_from_slice(&MAGIC.to_le_bytes());
+        buf[8..16].copy_from_slice(&self.offset.to_le_bytes());
+        buf[16..24].copy_from_slice(&self.len.to_le_bytes());
+        buf
+    }
+}
+
+/// A durable `key=value` store. Reads the full record set into memory on
+/// [`open`](Self::open); every mutating call persists the complete record
+/// set back to the backing file via a crash-safe scratch-then-commit
+/// sequence.
+pub struct ConfigStore {
+    file: Arc<dyn File>,
+    header: RwLock<Header>,
+    records: RwLock<BTreeMap<String, String>>,
+}
+
+impl ConfigStore {
+    /// Opens `file` as a config store, parsing its header and current
+    /// record set. An empty file is treated as a fresh, empty store.
+    pub fn open(file: Arc<dyn File>) -> Result<Self, SvsmError> {
+        if file.size() == 0 {
+            let header = Header {
+                offset: PRIMARY_SLOT_OFFSET as u64,
+                len: 0,
+            };
+            file.write(&header.serialize(), 0)?;
+            return Ok(ConfigStore {
+                file,
+                header: RwLock::new(header),
+                records: RwLock::new(BTreeMap::new()),
+            });
+        }
+
+        let mut raw_header = [0u8; HEADER_SIZE];
+        file.read(&mut raw_header, 0)?;
+        let header = Header::parse(&raw_header).ok_or(SvsmError::FileSystem(FsError::inval()))?;
+
+        let mut payload = vec![0u8; header.len as usize];
+        file.read(&mut payload, header.offset as usize)?;
+        let records = parse_records(&payload)?;
+
+        Ok(ConfigStore {
+            file,
+            header: RwLock::new(header),
+            records: RwLock::new(records),
+        })
+    }
+
+    /// Returns the value stored for `key`, if any.
+    pub fn get(&self, key: &str) -> Option<String> {
+        self.records.read().get(key).cloned()
+    }
+
+    /// Sets `key` to `value`, persisting the full record set.
+    pub fn set(&self, key: &str, value: &str) -> Result<(), SvsmError> {
+        let mut records = self.records.write();
+        records.insert(key.to_string(), value.to_string());
+        self.persist(&records)
+    }
+
+    /// Removes `key`, persisting the full record set. Removing a key that
+    /// is not present is not an error.
+    pub fn remove(&self, key: &str) -> Result<(), SvsmError> {
+        let mut records = self.records.write();
+        records.remove(key);
+        self.persist(&records)
+    }
+
+    /// Clears every record and persists the now-empty store.
+    pub fn erase(&self) -> Result<(), SvsmError> {
+        let mut records = self.records.write();
+        records.clear();
+        self.persist(&records)
+    }
+
+    /// Writes the crash-safe scratch-then-commit sequence:
+    ///
+    /// 1. Serialize `records` and write it to a scratch region that sits
+    ///    past whatever region the on-disk header currently references and
+    ///    past the part of the primary slot the new payload will fill, so
+    ///    the live record set is never touched by this step.
+    /// 2. Commit the header so it points at the scratch region. This is the
+    ///    single point at which the new record set becomes the one readers
+    ///    observe; a crash before this point leaves the old header and old
+    ///    (fully intact) data in place.
+    /// 3. Only now, with the header already pointing elsewhere, copy the
+    ///    payload down into the primary slot and re-commit the header to
+    ///    point back at it, then truncate away the now-unused scratch
+    ///    bytes. A crash during this step still leaves the header pointing
+    ///    at a fully valid copy of the data (the scratch one).
+    fn persist(&self, records: &BTreeMap<String, String>) -> Result<(), SvsmError> {
+        let payload = serialize_records(records);
+        let mut header = self.header.write();
+
+        // Never place scratch on top of the primary slot: it must also lie
+        // past the bytes the new payload will fill there in step 3.
+        let scratch_offset = (header.offset as usize + header.len as usize)
+            .max(PRIMARY_SLOT_OFFSET + payload.len().max(1));
+
+        self.file.write(&payload, scratch_offset)?;
+
+        *header = Header {
+            offset: scratch_offset as u64,
+            len: payload.len() as u64,
+        };
+        self.file.write(&header.serialize(), 0)?;
+
+        self.file.write(&payload, PRIMARY_SLOT_OFFSET)?;
+        *header = Header {
+            offset: PRIMARY_SLOT_OFFSET as u64,
+            len: payload.len() as u64,
+        };
+        self.file.write(&header.serialize(), 0)?;
+
+        self.file.truncate(PRIMARY_SLOT_OFFSET + payload.len())?;
+        Ok(())
+    }
+}
+
+fn serialize_records(records: &BTreeMap<String, String>) -> Vec<u8> {
+    let mut out = Vec::new();
+    for (key, value) in records {
+        out.extend_from_slice(key.as_bytes());
+        out.push(b'=');
+        out.extend_from_slice(value.as_bytes());
+        out.push(b'\n');
+    }
+    out
+}
+
+fn parse_records(buf: &[u8]) -> Result<BTreeMap<String, String>, SvsmError> {
+    let text = core::str::from_utf8(buf).map_err(|_| SvsmError::FileSystem(FsError::inval()))?;
+    let mut records = BTreeMap::new();
+
+    for line in text.split('\n') {
+        if line.is_empty() {
+            continue;
+        }
+        let (key, value) = line
+            .split_once('=')
+            .ok_or(SvsmError::FileSystem(FsError::inval()))?;
+        records.insert(key.to_string(), value.to_string());
+    }
+
+    Ok(records)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fs::ramfs::RamFile;
+
+    #[test]
+    fn round_trip_set_get() {
+        let file = RamFile::new();
+        let store = ConfigStore::open(file).unwrap();
+
+        store.set("launch.partition", "0").unwrap();
+        store.set("launch.vcpus", "4").unwrap();
+        assert_eq!(store.get("launch.partition").as_deref(), Some("0"));
+        assert_eq!(store.get("launch.vcpus").as_deref(), Some("4"));
+
+        store.remove("launch.vcpus").unwrap();
+        assert_eq!(store.get("launch.vcpus"), None);
+        assert_eq!(store.get("launch.partition").as_deref(), Some("0"));
+    }
+
+    #[test]
+    fn erase_clears_everything() {
+        let file = RamFile::new();
+        let store = ConfigStore::open(file).unwrap();
+
+        store.set("a", "1").unwrap();
+        store.erase().unwrap();
+        assert_eq!(store.get("a"), None);
+    }
+
+    #[test]
+    fn reopen_survives_a_crash_after_scratch_commit() {
+        let file = RamFile::new();
+        let store = ConfigStore::open(file.clone()).unwrap();
+        store.set("a", "1").unwrap();
+
+        // Manually perform just the scratch-write-then-header-commit half
+        // of persist() for a new record set, stopping before the primary
+        // slot is ever touched. This is exactly the on-disk state a power
+        // loss between those two steps would leave behind; reopening must
+        // still observe the new data purely via the scratch copy.
+        let mut records = BTreeMap::new();
+        records.insert("a".to_string(), "2".to_string());
+        let payload = serialize_records(&records);
+        let scratch_offset = PRIMARY_SLOT_OFFSET + 4096;
+        file.write(&payload, scratch_offset).unwrap();
+        let header = Header {
+            offset: scratch_offset as u64,
+            len: payload.len() as u64,
+        };
+        file.write(&header.serialize(), 0).unwrap();
+
+        let reopened = ConfigStore::open(file).unwrap();
+        assert_eq!(reopened.get("a").as_deref(), Some("2"));
+    }
+}
@@ -0,0 +1,272 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//
+// Copyright (c) 2023 SUSE LLC
+//
+// Author: Joerg Roedel <jroedel@suse.de>
+
+//! Loader for `newc`-format CPIO archives (the classic Linux initramfs
+//! format), used to populate an in-memory [`RamDirectory`] tree with a
+//! payload shipped alongside the SVSM at boot.
+
+use super::api::{Directory, DirEntry, File, FileName, MAX_FILENAME_LENGTH};
+use super::ramfs::RamDirectory;
+use crate::error::SvsmError;
+use alloc::sync::Arc;
+
+const CPIO_MAGIC: &[u8; 6] = b"070701";
+const CPIO_HEADER_SIZE: usize = 110;
+const CPIO_TRAILER: &str = "TRAILER!!!";
+
+const S_IFMT: u32 = 0o170000;
+const S_IFDIR: u32 = 0o040000;
+
+fn hex_field(header: &[u8], offset: usize) -> Result<u32, SvsmError> {
+    let field = core::str::from_utf8(&header[offset..offset + 8])
+        .map_err(|_| SvsmError::FileSystem(super::api::FsError::inval()))?;
+    u32::from_str_radix(field, 16).map_err(|_| SvsmError::FileSystem(super::api::FsError::inval()))
+}
+
+fn align4(len: usize) -> usize {
+    len.next_multiple_of(4)
+}
+
+struct CpioEntry<'a> {
+    mode: u32,
+    file_size: usize,
+    name: &'a str,
+    data: &'a [u8],
+    /// Total size of header + name + data, all padded to 4-byte boundaries,
+    /// so the caller can advance to the next entry.
+    entry_len: usize,
+}
+
+fn parse_entry(buf: &[u8]) -> Result<CpioEntry<'_>, SvsmError> {
+    if buf.len() < CPIO_HEADER_SIZE || &buf[0..6] != CPIO_MAGIC {
+        return Err(SvsmError::FileSystem(super::api::FsError::inval()));
+    }
+
+    let mode = hex_field(buf, 14)?;
+    let file_size = hex_field(buf, 54)? as usize;
+    let name_size = hex_field(buf, 94)? as usize;
+
+    // name_size includes the terminating NUL byte, so a well-formed entry
+    // (even an empty name) always has name_size >= 1; a zero here would
+    // underflow the `name_end - 1` below instead of leaving room for it.
+    if name_size == 0 {
+        return Err(SvsmError::FileSystem(super::api::FsError::inval()));
+    }
+
+    let name_start = CPIO_HEADER_SIZE;
+    let name_end = name_start + name_size;
+    if buf.len() < name_end {
+        return Err(SvsmError::FileSystem(super::api::FsError::inval()));
+    }
+    let name = core::str::from_utf8(&buf[name_start..name_end - 1])
+        .map_err(|_| SvsmError::FileSystem(super::api::FsError::inval()))?;
+
+    let data_start = align4(name_end);
+    let data_end = data_start + file_size;
+    if buf.len() < data_end {
+        return Err(SvsmError::FileSystem(super::api::FsError::inval()));
+    }
+    let data = &buf[data_start..data_end];
+
+    Ok(CpioEntry {
+        mode,
+        file_size,
+        name,
+        data,
+        entry_len: align4(data_end),
+    })
+}
+
+/// Strips a CPIO path down to one that is relative to the archive root,
+/// the way real `newc` archives are laid out in practice (entries named
+/// plain `"."`, for the root itself, and `"./foo/bar"` for everything
+/// else, as produced by `cpio -o --format=newc` from a `find` listing or
+/// the kernel's `gen_init_cpio`). Returns `""` for the root entry itself.
+fn normalize_path(path: &str) -> &str {
+    if path == "." {
+        return "";
+    }
+
+    let mut path = path;
+    while let Some(rest) = path.strip_prefix("./") {
+        path = rest;
+    }
+    path
+}
+
+/// Walks `path`'s directory components relative to `root`, creating any
+/// that do not exist yet, and returns the final parent directory together
+/// with the leaf component's name.
+fn resolve_parent(
+    root: &Arc<RamDirectory>,
+    path: &str,
+) -> Result<(Arc<dyn Directory>, FileName), SvsmError> {
+    let mut components = path.split('/').filter(|c| !c.is_empty()).peekable();
+    let mut dir: Arc<dyn Directory> = root.clone();
+
+    loop {
+        let component = components
+            .next()
+            .ok_or(SvsmError::FileSystem(super::api::FsError::inval()))?;
+        if component.len() > MAX_FILENAME_LENGTH {
+            return Err(SvsmError::FileSystem(super::api::FsError::name_too_long()));
+        }
+        let name = FileName::from(component.as_bytes());
+
+        if components.peek().is_none() {
+            return Ok((dir, name));
+        }
+
+        dir = match dir.lookup_entry(name) {
+            Ok(DirEntry::Directory(d)) => d,
+            Ok(DirEntry::File(_)) => {
+                return Err(SvsmError::FileSystem(super::api::FsError::not_a_directory()))
+            }
+            Err(_) => dir.create_directory(name)?,
+        };
+    }
+}
+
+/// Parses a `newc`-format CPIO archive and populates a fresh in-memory
+/// directory tree with its contents, returning the tree's root.
+pub fn load_initramfs(image: &[u8]) -> Result<Arc<dyn Directory>, SvsmError> {
+    let root = RamDirectory::new();
+    let mut offset = 0;
+
+    while offset < image.len() {
+        let entry = parse_entry(&image[offset..])?;
+        if entry.name == CPIO_TRAILER {
+            break;
+        }
+
+        let path = normalize_path(entry.name);
+        if path.is_empty() {
+            // The root directory entry itself ("." or, after stripping a
+            // leading "./", nothing left); there's nothing to create.
+            offset += entry.entry_len;
+            continue;
+        }
+
+        let (parent, name) = resolve_parent(&root, path)?;
+
+        if entry.mode & S_IFMT == S_IFDIR {
+            // Root-level directories may already exist from earlier
+            // component creation; that is not an error for the CPIO's own
+            // directory record.
+            let _ = parent.create_directory(name);
+        } else {
+            let file = parent.create_file(name)?;
+            if entry.file_size > 0 {
+                file.write(entry.data, 0)?;
+            }
+        }
+
+        offset += entry.entry_len;
+    }
+
+    Ok(root)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec::Vec;
+
+    const S_IFREG_MODE: u32 = 0o100644;
+    const S_IFDIR_MODE: u32 = 0o040755;
+
+    fn push_entry(buf: &mut Vec<u8>, name: &str, mode: u32, data: &[u8]) {
+        let name_with_nul = {
+            let mut n = Vec::from(name.as_bytes());
+            n.push(0);
+            n
+        };
+
+        buf.extend_from_slice(CPIO_MAGIC);
+        let fields = [0u32, mode, 0, 0, 1, 0, data.len() as u32, 0, 0, 0, 0, name_with_nul.len() as u32, 0];
+        for f in fields {
+            buf.extend_from_slice(alloc::format!("{:08x}", f).as_bytes());
+        }
+        buf.extend_from_slice(&name_with_nul);
+        while buf.len() % 4 != 0 {
+            buf.push(0);
+        }
+        buf.extend_from_slice(data);
+        while buf.len() % 4 != 0 {
+            buf.push(0);
+        }
+    }
+
+    fn build_archive(entries: &[(&str, u32, &[u8])]) -> Vec<u8> {
+        let mut buf = Vec::new();
+        for (name, mode, data) in entries {
+            push_entry(&mut buf, name, *mode, data);
+        }
+        push_entry(&mut buf, CPIO_TRAILER, 0, &[]);
+        buf
+    }
+
+    #[test]
+    fn parse_entry_rejects_zero_namesize() {
+        // A well-formed entry's namesize always counts the terminating NUL,
+        // so it is never zero; a malformed header claiming namesize == 0
+        // must not underflow the `name_end - 1` slice bound.
+        let mut buf = Vec::new();
+        buf.extend_from_slice(CPIO_MAGIC);
+        let fields = [0u32; 13];
+        for f in fields {
+            buf.extend_from_slice(alloc::format!("{:08x}", f).as_bytes());
+        }
+
+        let err = parse_entry(&buf).unwrap_err();
+        assert!(matches!(
+            err,
+            SvsmError::FileSystem(super::super::api::FsError::Inval)
+        ));
+    }
+
+    #[test]
+    fn normalize_path_strips_dot_root_and_dot_slash_prefix() {
+        assert_eq!(normalize_path("."), "");
+        assert_eq!(normalize_path("./foo"), "foo");
+        assert_eq!(normalize_path("./foo/bar"), "foo/bar");
+        assert_eq!(normalize_path("foo/bar"), "foo/bar");
+    }
+
+    #[test]
+    fn load_initramfs_handles_leading_dot_slash_layout() {
+        let image = build_archive(&[
+            (".", S_IFDIR_MODE, &[]),
+            ("./foo", S_IFDIR_MODE, &[]),
+            ("./foo/bar.txt", S_IFREG_MODE, b"hello"),
+        ]);
+
+        let root = load_initramfs(&image).unwrap();
+
+        // "foo" must live directly under the root, not nested under a
+        // spurious "." directory.
+        let foo = match root.lookup_entry(FileName::from(b"foo".as_slice())).unwrap() {
+            DirEntry::Directory(d) => d,
+            DirEntry::File(_) => panic!("expected foo to be a directory"),
+        };
+
+        let bar = match foo
+            .lookup_entry(FileName::from(b"bar.txt".as_slice()))
+            .unwrap()
+        {
+            DirEntry::File(f) => f,
+            DirEntry::Directory(_) => panic!("expected bar.txt to be a file"),
+        };
+
+        let mut data = [0u8; 5];
+        assert_eq!(bar.read(&mut data, 0).unwrap(), 5);
+        assert_eq!(&data, b"hello");
+
+        // The literal "." entry must not have created a child named "."
+        // under the root.
+        assert!(root.lookup_entry(FileName::from(b".".as_slice())).is_err());
+    }
+}
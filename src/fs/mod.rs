@@ -0,0 +1,14 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//
+// Copyright (c) 2023 SUSE LLC
+//
+// Author: Joerg Roedel <jroedel@suse.de>
+
+pub mod api;
+pub mod config;
+pub mod cpio;
+pub mod ext2;
+pub mod mount;
+pub mod ramfs;
+
+pub use api::*;
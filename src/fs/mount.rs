@@ -0,0 +1,128 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//
+// Copyright (c) 2023 SUSE LLC
+//
+// Author: Joerg Roedel <jroedel@suse.de>
+
+//! A mount table mapping path prefixes (`fs:`, `ram:`, `cfg:`, ...) to
+//! registered [`Directory`] roots, plus [`resolve`] to walk a single
+//! `prefix:/a/b/c`-style path across that table down to its final
+//! [`DirEntry`].
+
+extern crate alloc;
+use alloc::string::{String, ToString};
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+
+use spin::RwLock;
+
+use super::api::{Directory, DirEntry, FileName, FsError, MAX_FILENAME_LENGTH};
+use crate::error::SvsmError;
+
+struct Mount {
+    prefix: String,
+    root: Arc<dyn Directory>,
+}
+
+static MOUNTS: RwLock<Vec<Mount>> = RwLock::new(Vec::new());
+
+/// Registers `dir` as the root backend for paths starting with `prefix`
+/// (e.g. `"fs:"`, `"ram:"`, `"cfg:"`). Replaces any existing mount for the
+/// same prefix.
+pub fn register_mount(prefix: &str, dir: Arc<dyn Directory>) {
+    let mut mounts = MOUNTS.write();
+    mounts.retain(|m| m.prefix != prefix);
+    mounts.push(Mount {
+        prefix: prefix.to_string(),
+        root: dir,
+    });
+}
+
+/// Removes the mount registered for `prefix`, if any.
+pub fn unmount(prefix: &str) {
+    MOUNTS.write().retain(|m| m.prefix != prefix);
+}
+
+fn find_mount(path: &str) -> Result<(Arc<dyn Directory>, &str), SvsmError> {
+    let mounts = MOUNTS.read();
+    let mount = mounts
+        .iter()
+        .find(|m| path.starts_with(m.prefix.as_str()))
+        .ok_or(SvsmError::FileSystem(FsError::file_not_found()))?;
+    Ok((mount.root.clone(), &path[mount.prefix.len()..]))
+}
+
+/// Resolves a `/`-delimited path such as `"fs:/boot/config"` by picking the
+/// mount whose prefix the path starts with and walking
+/// `Directory::lookup_entry` from that mount's root down to the final
+/// component. The walk stays inside that one root; mounts are not nested.
+pub fn resolve(path: &str) -> Result<DirEntry, SvsmError> {
+    let (root, rest) = find_mount(path)?;
+    let mut dir = root;
+    let mut components = rest.split('/').filter(|c| !c.is_empty()).peekable();
+
+    let Some(mut component) = components.next() else {
+        return Ok(DirEntry::Directory(dir));
+    };
+
+    loop {
+        if component.len() > MAX_FILENAME_LENGTH {
+            return Err(SvsmError::FileSystem(FsError::name_too_long()));
+        }
+        let name = FileName::from(component.as_bytes());
+        let entry = dir.lookup_entry(name)?;
+
+        match components.next() {
+            None => return Ok(entry),
+            Some(next) => match entry {
+                DirEntry::Directory(d) => {
+                    dir = d;
+                    component = next;
+                }
+                DirEntry::File(_) => {
+                    return Err(SvsmError::FileSystem(FsError::not_a_directory()));
+                }
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fs::ramfs::RamDirectory;
+
+    #[test]
+    fn resolve_walks_across_mount_prefix() {
+        let root = RamDirectory::new();
+        root.create_file(FileName::from(b"config".as_slice())).unwrap();
+        register_mount("fs:", root);
+
+        match resolve("fs:/config").unwrap() {
+            DirEntry::File(_) => (),
+            DirEntry::Directory(_) => panic!("expected config to be a file"),
+        }
+
+        unmount("fs:");
+    }
+
+    #[test]
+    fn resolve_rejects_unknown_prefix() {
+        let err = resolve("nope:/anything").unwrap_err();
+        assert!(matches!(err, SvsmError::FileSystem(FsError::FileNotFound)));
+    }
+
+    #[test]
+    fn resolve_rejects_oversized_path_component() {
+        let root = RamDirectory::new();
+        register_mount("long:", root);
+
+        let long_name: alloc::string::String =
+            core::iter::repeat('a').take(MAX_FILENAME_LENGTH + 1).collect();
+        let path = alloc::format!("long:/{}", long_name);
+        let err = resolve(&path).unwrap_err();
+        assert!(matches!(err, SvsmError::FileSystem(FsError::NameTooLong)));
+
+        unmount("long:");
+    }
+}
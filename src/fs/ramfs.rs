@@ -0,0 +1,220 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//
+// Copyright (c) 2023 SUSE LLC
+//
+// Author: Joerg Roedel <jroedel@suse.de>
+
+//! A simple in-memory implementation of the [`File`]/[`Directory`] traits,
+//! used to hold a tree of files that only need to live for the lifetime of
+//! the SVSM, such as the contents of an initramfs.
+
+extern crate alloc;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+
+use spin::RwLock;
+
+use super::api::{Directory, DirEntry, File, FileName, FsError};
+use crate::error::SvsmError;
+
+/// An in-memory file. Its contents live entirely in a heap-allocated
+/// buffer that grows on write/truncate.
+#[derive(Default)]
+pub struct RamFile {
+    data: RwLock<Vec<u8>>,
+}
+
+impl RamFile {
+    pub fn new() -> Arc<Self> {
+        Arc::new(RamFile {
+            data: RwLock::new(Vec::new()),
+        })
+    }
+}
+
+impl File for RamFile {
+    fn read(&self, buf: &mut [u8], offset: usize) -> Result<usize, SvsmError> {
+        let data = self.data.read();
+        if offset >= data.len() {
+            return Ok(0);
+        }
+
+        let len = buf.len().min(data.len() - offset);
+        buf[..len].copy_from_slice(&data[offset..offset + len]);
+        Ok(len)
+    }
+
+    fn write(&self, buf: &[u8], offset: usize) -> Result<usize, SvsmError> {
+        let mut data = self.data.write();
+        let end = offset + buf.len();
+        if end > data.len() {
+            data.resize(end, 0);
+        }
+        data[offset..end].copy_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn truncate(&self, size: usize) -> Result<usize, SvsmError> {
+        self.data.write().resize(size, 0);
+        Ok(size)
+    }
+
+    fn size(&self) -> usize {
+        self.data.read().len()
+    }
+}
+
+struct RamDirectoryEntry {
+    name: FileName,
+    entry: DirEntry,
+}
+
+/// An in-memory directory, holding its children as a simple `Vec` since
+/// directory trees are expected to stay small.
+#[derive(Default)]
+pub struct RamDirectory {
+    entries: RwLock<Vec<RamDirectoryEntry>>,
+}
+
+impl RamDirectory {
+    pub fn new() -> Arc<Self> {
+        Arc::new(RamDirectory {
+            entries: RwLock::new(Vec::new()),
+        })
+    }
+}
+
+impl Directory for RamDirectory {
+    fn list(&self) -> Vec<FileName> {
+        self.entries.read().iter().map(|e| e.name).collect()
+    }
+
+    fn lookup_entry(&self, name: FileName) -> Result<DirEntry, SvsmError> {
+        self.entries
+            .read()
+            .iter()
+            .find(|e| e.name == name)
+            .map(|e| e.entry.clone())
+            .ok_or(SvsmError::FileSystem(FsError::file_not_found()))
+    }
+
+    fn create_file(&self, name: FileName) -> Result<Arc<dyn File>, SvsmError> {
+        if self.lookup_entry(name).is_ok() {
+            return Err(SvsmError::FileSystem(FsError::file_exists()));
+        }
+
+        let file = RamFile::new();
+        self.entries.write().push(RamDirectoryEntry {
+            name,
+            entry: DirEntry::File(file.clone()),
+        });
+        Ok(file)
+    }
+
+    fn create_directory(&self, name: FileName) -> Result<Arc<dyn Directory>, SvsmError> {
+        if self.lookup_entry(name).is_ok() {
+            return Err(SvsmError::FileSystem(FsError::file_exists()));
+        }
+
+        let dir = RamDirectory::new();
+        self.entries.write().push(RamDirectoryEntry {
+            name,
+            entry: DirEntry::Directory(dir.clone()),
+        });
+        Ok(dir)
+    }
+
+    fn unlink(&self, name: FileName) -> Result<(), SvsmError> {
+        let mut entries = self.entries.write();
+        let len_before = entries.len();
+        entries.retain(|e| e.name != name);
+        if entries.len() == len_before {
+            return Err(SvsmError::FileSystem(FsError::file_not_found()));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn file_write_read_round_trip() {
+        let file = RamFile::new();
+        file.write(b"hello", 0).unwrap();
+        file.write(b"!", 5).unwrap();
+        assert_eq!(file.size(), 6);
+
+        let mut buf = [0u8; 6];
+        assert_eq!(file.read(&mut buf, 0).unwrap(), 6);
+        assert_eq!(&buf, b"hello!");
+    }
+
+    #[test]
+    fn file_read_past_end_returns_zero() {
+        let file = RamFile::new();
+        file.write(b"hi", 0).unwrap();
+
+        let mut buf = [0u8; 4];
+        assert_eq!(file.read(&mut buf, 2).unwrap(), 0);
+        assert_eq!(file.read(&mut buf, 100).unwrap(), 0);
+    }
+
+    #[test]
+    fn file_truncate_shrinks_and_grows() {
+        let file = RamFile::new();
+        file.write(b"hello", 0).unwrap();
+
+        file.truncate(2).unwrap();
+        assert_eq!(file.size(), 2);
+
+        file.truncate(4).unwrap();
+        assert_eq!(file.size(), 4);
+        let mut buf = [0u8; 4];
+        file.read(&mut buf, 0).unwrap();
+        assert_eq!(&buf, &[b'h', b'e', 0, 0]);
+    }
+
+    #[test]
+    fn directory_create_and_lookup_round_trip() {
+        let root = RamDirectory::new();
+        root.create_file(FileName::from(b"a".as_slice())).unwrap();
+        root.create_directory(FileName::from(b"b".as_slice()))
+            .unwrap();
+
+        match root.lookup_entry(FileName::from(b"a".as_slice())).unwrap() {
+            DirEntry::File(_) => (),
+            DirEntry::Directory(_) => panic!("expected a to be a file"),
+        }
+        match root.lookup_entry(FileName::from(b"b".as_slice())).unwrap() {
+            DirEntry::Directory(_) => (),
+            DirEntry::File(_) => panic!("expected b to be a directory"),
+        }
+        assert_eq!(root.list().len(), 2);
+    }
+
+    #[test]
+    fn directory_create_duplicate_name_fails() {
+        let root = RamDirectory::new();
+        root.create_file(FileName::from(b"a".as_slice())).unwrap();
+        let err = root
+            .create_directory(FileName::from(b"a".as_slice()))
+            .unwrap_err();
+        assert!(matches!(err, SvsmError::FileSystem(FsError::FileExists)));
+    }
+
+    #[test]
+    fn directory_unlink_removes_entry() {
+        let root = RamDirectory::new();
+        root.create_file(FileName::from(b"a".as_slice())).unwrap();
+
+        root.unlink(FileName::from(b"a".as_slice())).unwrap();
+        assert!(root.lookup_entry(FileName::from(b"a".as_slice())).is_err());
+
+        let err = root
+            .unlink(FileName::from(b"a".as_slice()))
+            .unwrap_err();
+        assert!(matches!(err, SvsmError::FileSystem(FsError::FileNotFound)));
+    }
+}